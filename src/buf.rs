@@ -0,0 +1,54 @@
+//! `Buffer`: the single fixed-size staging buffer `TcpStream`/`UnixStream`
+//! read and write through.
+//!
+//! A read or write can take more than one poll to complete, and
+//! `AsyncBufRead::poll_fill_buf` in particular can be polled repeatedly
+//! without an intervening `consume` and must keep returning the same
+//! bytes. So rather than re-running the caller's fill closure on every
+//! poll, `Buffer` remembers what it's already filled and only calls back
+//! into the closure once the filled region has been fully consumed.
+
+use std::io;
+use std::task::Poll;
+
+use futures_core::ready;
+
+use crate::ring::Cancellation;
+
+const CAPACITY: usize = 8 * 1024;
+
+pub(crate) struct Buffer {
+    storage: Box<[u8; CAPACITY]>,
+    pos: usize,
+    len: usize,
+}
+
+impl Default for Buffer {
+    fn default() -> Buffer {
+        Buffer { storage: Box::new([0; CAPACITY]), pos: 0, len: 0 }
+    }
+}
+
+impl Buffer {
+    pub fn fill_buf(&mut self, f: impl FnOnce(&mut [u8]) -> Poll<io::Result<u32>>) -> Poll<io::Result<&[u8]>> {
+        if self.pos >= self.len {
+            self.pos = 0;
+            self.len = ready!(f(&mut self.storage[..]))? as usize;
+        }
+        Poll::Ready(Ok(&self.storage[self.pos..self.len]))
+    }
+
+    pub fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.len);
+    }
+
+    pub fn clear(&mut self) {
+        self.pos = 0;
+        self.len = 0;
+    }
+
+    pub fn cancellation(&mut self) -> Cancellation {
+        self.clear();
+        Cancellation::from(())
+    }
+}
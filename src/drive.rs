@@ -0,0 +1,178 @@
+//! `Drive`: the interface `Ring` and `Submission` use to submit SQEs to an
+//! io_uring instance and retrieve their completions.
+
+use std::io;
+use std::task::{Context, Poll};
+
+use crate::event::{Event, SQE, SQEs};
+use crate::ring::Cancellation;
+use crate::Submission;
+
+/// Owns (or has access to) an io_uring instance capable of submitting SQEs
+/// prepared by a caller and handing back the `res`/`flags` of the
+/// resulting CQEs.
+///
+/// `Ring` and `Submission` are generic over `Drive` so callers can plug in
+/// whatever reactor integration they want; `demo::DemoDriver` is a
+/// minimal, single-threaded implementation good enough for doctests and
+/// small example binaries.
+pub trait Drive: Sized {
+    /// Submit `count` SQEs prepared by `prepare`, resolving to the `res`
+    /// field of the CQE for the SQE `prepare` returns. Completions of any
+    /// other SQEs submitted alongside it (e.g. a linked
+    /// `IORING_OP_LINK_TIMEOUT`) are not reported.
+    fn poll(
+        &mut self,
+        ctx: &mut Context<'_>,
+        count: u32,
+        prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>,
+    ) -> Poll<io::Result<i32>>;
+
+    /// Like `poll`, but for SQEs the kernel may complete more than once.
+    /// See `Ring::poll_multishot` for what `armed` means. Resolves to the
+    /// completion's `res` alongside its raw `flags` — the flags are
+    /// returned outside the `Result` because a caller needs them even on
+    /// an error completion, to tell whether the kernel is still going to
+    /// produce more completions for this SQE (`IORING_CQE_F_MORE`) or has
+    /// dropped the registration.
+    fn poll_multishot(
+        &mut self,
+        ctx: &mut Context<'_>,
+        armed: bool,
+        count: u32,
+        prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>,
+    ) -> Poll<(io::Result<i32>, u32)>;
+
+    fn cancel(&mut self, cancellation: Cancellation);
+
+    /// Submit a one-shot `Event`, taking ownership of this driver for the
+    /// life of the submission.
+    fn submit<E: Event>(self, event: E) -> Submission<E, Self> {
+        Submission::new(event, self)
+    }
+}
+
+pub mod demo {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::task::{Context, Poll};
+
+    use iou::IoUring;
+
+    use crate::event::{SQE, SQEs};
+    use crate::ring::Cancellation;
+    use super::Drive;
+
+    const DEFAULT_ENTRIES: u32 = 32;
+    // Tag applied to whichever SQE `prepare` hands back, so a logical
+    // operation's own completion can be told apart from a companion SQE
+    // submitted alongside it (e.g. a linked `IORING_OP_LINK_TIMEOUT`).
+    const PRIMARY: u64 = 1;
+
+    /// A minimal, blocking reference `Drive`. It owns a small io_uring
+    /// instance behind an `Rc` (cheap to clone onto accepted connections,
+    /// matching how the rest of this crate's socket types hand off their
+    /// `Ring` on accept/connect) and drives every operation to completion
+    /// synchronously inside `poll`/`poll_multishot`, rather than
+    /// integrating with a real reactor and waking a task later.
+    ///
+    /// This exists so the doctests and demo binaries in this crate have a
+    /// working `Drive` without pulling in a full executor; it's not meant
+    /// for production use on a multi-tasked runtime.
+    #[derive(Clone)]
+    pub struct DemoDriver {
+        ring: Rc<RefCell<IoUring>>,
+    }
+
+    impl Default for DemoDriver {
+        fn default() -> DemoDriver {
+            let ring = IoUring::new(DEFAULT_ENTRIES).expect("failed to set up an io_uring instance");
+            DemoDriver { ring: Rc::new(RefCell::new(ring)) }
+        }
+    }
+
+    impl DemoDriver {
+        // `flags` is returned alongside the `Result` rather than packed
+        // inside its `Ok`, so a caller (namely `poll_multishot`) can still
+        // read `IORING_CQE_F_MORE` off of an error completion.
+        fn submit_and_wait(&self, count: u32, prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>)
+            -> (io::Result<i32>, u32)
+        {
+            let mut ring = self.ring.borrow_mut();
+            {
+                let mut sqs = ring.sq().prepare_sqes(count).expect("submission queue is full");
+                let mut primary = prepare(&mut sqs);
+                primary.set_user_data(PRIMARY);
+            }
+            if let Err(e) = ring.sq().submit() {
+                return (Err(e), 0);
+            }
+
+            // `count` SQEs went in (the op's own, plus e.g. a linked
+            // LINK_TIMEOUT's), so `count` CQEs have to come back out, or a
+            // companion completion is left sitting in the queue forever.
+            // Only the one tagged `PRIMARY` above is what this call
+            // resolves to; the rest (a LINK_TIMEOUT's own `-ETIME`, say)
+            // are just drained.
+            let mut primary_result = None;
+            for _ in 0..count {
+                let cqe = loop {
+                    match ring.cq().peek_for_cqe() {
+                        Some(cqe) => break cqe,
+                        None      => {
+                            if let Err(e) = ring.sq().submit_and_wait(1) {
+                                return (Err(e), 0);
+                            }
+                        }
+                    }
+                };
+                if cqe.user_data() == PRIMARY {
+                    let flags = cqe.flags();
+                    primary_result = Some((cqe.result().map(|n| n as i32), flags));
+                }
+            }
+            primary_result.expect("the primary SQE's CQE was never observed")
+        }
+    }
+
+    impl Drive for DemoDriver {
+        fn poll(&mut self, _ctx: &mut Context<'_>, count: u32, prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>)
+            -> Poll<io::Result<i32>>
+        {
+            Poll::Ready(self.submit_and_wait(count, prepare).0)
+        }
+
+        fn poll_multishot(
+            &mut self,
+            _ctx: &mut Context<'_>,
+            armed: bool,
+            count: u32,
+            prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>,
+        ) -> Poll<(io::Result<i32>, u32)> {
+            if !armed {
+                return Poll::Ready(self.submit_and_wait(count, prepare));
+            }
+            // Already armed: the SQE is still live, so wait for its next CQE.
+            let mut ring = self.ring.borrow_mut();
+            loop {
+                match ring.cq().peek_for_cqe() {
+                    Some(cqe) if cqe.user_data() == PRIMARY => {
+                        let flags = cqe.flags();
+                        return Poll::Ready((cqe.result().map(|n| n as i32), flags));
+                    }
+                    Some(_) => { }
+                    None    => if let Err(e) = ring.sq().submit_and_wait(1) { return Poll::Ready((Err(e), 0)); },
+                }
+            }
+        }
+
+        fn cancel(&mut self, cancellation: Cancellation) {
+            // Every op this driver submits runs to completion
+            // synchronously before `poll` returns, so by the time a
+            // `Cancellation` reaches here the kernel is already done with
+            // whatever it was guarding.
+            drop(cancellation);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::mem::ManuallyDrop;
+use std::os::unix::io::RawFd;
+
+use iou::sqe::{SockAddr, SubmissionFlags, Timespec, TimeoutFlags};
+
+use super::{Event, SQE, SQEs, Cancellation};
+
+pub struct Connect {
+    pub fd: RawFd,
+    pub addr: Box<SockAddr>,
+    pub timeout: Option<Box<Timespec>>,
+}
+
+impl Event for Connect {
+    fn sqes_needed(&self) -> u32 {
+        if self.timeout.is_some() { 2 } else { 1 }
+    }
+
+    unsafe fn prepare<'sq>(&mut self, sqs: &mut SQEs<'sq>) -> SQE<'sq> {
+        match &mut self.timeout {
+            Some(timeout) => {
+                let mut connect = sqs.next().unwrap();
+                connect.prep_connect(self.fd, &self.addr);
+                connect.set_flags(SubmissionFlags::IO_LINK);
+                let mut link_timeout = sqs.next().unwrap();
+                link_timeout.prep_link_timeout(timeout, TimeoutFlags::empty());
+                connect
+            }
+            None => {
+                let mut sqe = sqs.single().unwrap();
+                sqe.prep_connect(self.fd, &self.addr);
+                sqe
+            }
+        }
+    }
+
+    fn cancel(this: ManuallyDrop<Self>) -> Cancellation {
+        let this = ManuallyDrop::into_inner(this);
+        Cancellation::from((this.addr, this.timeout))
+    }
+}
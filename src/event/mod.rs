@@ -0,0 +1,34 @@
+use std::io;
+use std::mem::ManuallyDrop;
+
+pub mod accept;
+pub mod connect;
+
+pub(crate) use iou::sqe::{SQE, SQEs};
+
+pub use crate::ring::Cancellation;
+
+/// A single io_uring operation: how many SQEs it needs, how to fill them
+/// in, and what to do with whatever it owns if it's cancelled before the
+/// kernel completes it.
+pub trait Event {
+    fn sqes_needed(&self) -> u32;
+
+    /// # Safety
+    /// The returned `SQE` must not be submitted more than once, and `sqs`
+    /// must have at least `sqes_needed()` entries reserved in it.
+    unsafe fn prepare<'sq>(&mut self, sqs: &mut SQEs<'sq>) -> SQE<'sq>;
+
+    fn cancel(this: ManuallyDrop<Self>) -> Cancellation where Self: Sized;
+}
+
+// A timed-out op surfaces as its primary SQE completing with -ECANCELED:
+// that's the kernel cancelling it because the linked LINK_TIMEOUT SQE
+// fired first. Translate that into `ErrorKind::TimedOut` so a caller can
+// tell a timeout apart from an arbitrary cancellation.
+pub(crate) fn map_timeout(err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::ECANCELED) => io::Error::new(io::ErrorKind::TimedOut, err),
+        _ => err,
+    }
+}
@@ -0,0 +1,14 @@
+//! `ringbahn`: an experimental asynchronous runtime for `io_uring`, built
+//! directly on `iou`.
+
+pub mod drive;
+pub mod net;
+pub mod unix;
+
+pub(crate) mod event;
+pub(crate) mod ring;
+
+mod buf;
+mod submission;
+
+pub use submission::Submission;
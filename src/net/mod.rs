@@ -0,0 +1,4 @@
+//! Networking bindings for `ringbahn`.
+
+pub mod tcp;
+pub mod udp;
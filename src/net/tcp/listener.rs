@@ -19,12 +19,17 @@ pub struct TcpListener<D: Drive = DemoDriver> {
     fd: RawFd,
     active: Op,
     addr: Option<Box<iou::sqe::SockAddrStorage>>,
+    // Whether a multishot accept SQE is still armed in the kernel. While
+    // true, polling again waits on the existing submission instead of
+    // issuing a fresh one.
+    armed: bool,
 }
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 enum Op {
     Nothing = 0,
     Accept,
+    AcceptMultishot,
     Close,
     Closed,
 }
@@ -47,10 +52,15 @@ impl<D: Drive> TcpListener<D> {
         Ok(TcpListener {
             active: Op::Nothing,
             addr: None,
+            armed: false,
             fd, ring,
         })
     }
 
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        super::local_addr(self.fd)
+    }
+
     pub fn close(&mut self) -> Close<D> where D: Unpin {
         Pin::new(self).close_pinned()
     }
@@ -60,23 +70,33 @@ impl<D: Drive> TcpListener<D> {
     }
 
     fn guard_op(self: Pin<&mut Self>, op: Op) {
-        let (ring, addr, active) = self.split();
-        if *active == Op::Closed {
+        let this = unsafe { Pin::get_unchecked_mut(self) };
+        if this.active == Op::Closed {
             panic!("Attempted to perform IO on a closed TcpListener");
-        } else if *active != Op::Nothing && *active != op {
-            ring.cancel_pinned(Cancellation::from(addr.take()));
+        } else if this.active != Op::Nothing && this.active != op {
+            // Cancelling an armed multishot registration drops it in the
+            // kernel, so the stale `armed` has to go with it — otherwise
+            // switching back to `AcceptMultishot` later would poll with
+            // `armed: true` against a registration that no longer exists.
+            if this.active == Op::AcceptMultishot {
+                this.armed = false;
+            }
+            let ring = unsafe { Pin::new_unchecked(&mut this.ring) };
+            ring.cancel_pinned(Cancellation::from(this.addr.take()));
         }
-        *active = op;
+        this.active = op;
     }
 
     fn cancel(&mut self) {
         let cancellation = match self.active {
-            Op::Accept  => Cancellation::from(self.addr.take()),
-            Op::Close   => Cancellation::from(()),
-            Op::Closed  => return,
-            Op::Nothing => return,
+            Op::Accept          => Cancellation::from(self.addr.take()),
+            Op::AcceptMultishot => Cancellation::from(()),
+            Op::Close           => Cancellation::from(()),
+            Op::Closed          => return,
+            Op::Nothing         => return,
         };
         self.active = Op::Nothing;
+        self.armed = false;
         self.ring.cancel(cancellation);
     }
 
@@ -84,6 +104,10 @@ impl<D: Drive> TcpListener<D> {
         self.split().1.take();
     }
 
+    fn set_armed(self: Pin<&mut Self>, armed: bool) {
+        unsafe { Pin::get_unchecked_mut(self).armed = armed; }
+    }
+
     fn ring(self: Pin<&mut Self>) -> Pin<&mut Ring<D>> {
         self.split().0
     }
@@ -184,6 +208,39 @@ impl<D: Drive + Clone> TcpListener<D> {
         }))? as RawFd;
         Poll::Ready(Ok(TcpStream::from_fd(fd, self.ring().clone())))
     }
+
+    /// Like `incoming_no_addr`, but arms a single multishot accept SQE and
+    /// streams connections from the completions it generates instead of
+    /// re-submitting a fresh accept for every client.
+    pub fn incoming_multishot(&mut self) -> IncomingMultishot<'_, D> where D: Unpin {
+        Pin::new(self).incoming_multishot_pinned()
+    }
+
+    pub fn incoming_multishot_pinned(self: Pin<&mut Self>) -> IncomingMultishot<'_, D> {
+        IncomingMultishot { socket: self }
+    }
+
+    pub fn poll_accept_multishot(mut self: Pin<&mut Self>, ctx: &mut Context<'_>)
+        -> Poll<io::Result<TcpStream<D>>>
+    {
+        self.as_mut().guard_op(Op::AcceptMultishot);
+        let fd = self.fd;
+        let armed = self.armed;
+        let (result, more) = ready!(self.as_mut().ring().poll_multishot(ctx, armed, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe {
+                sqe.prep_accept_multishot(fd, SockFlag::empty());
+            }
+            sqe
+        }));
+        // `more` has to be applied before `?` propagates an error: a CQE
+        // without `IORING_CQE_F_MORE` means the kernel has dropped the
+        // multishot registration even when it's carrying an error (e.g.
+        // under resource pressure), so the next poll has to re-arm.
+        self.as_mut().set_armed(more);
+        let result = result? as RawFd;
+        Poll::Ready(Ok(TcpStream::from_fd(result, self.ring().clone())))
+    }
 }
 
 impl<D: Drive> Drop for TcpListener<D> {
@@ -258,6 +315,19 @@ impl<'a, D: Drive + Clone> Stream for IncomingNoAddr<'a, D> {
     }
 }
 
+pub struct IncomingMultishot<'a, D: Drive> {
+    socket: Pin<&'a mut TcpListener<D>>,
+}
+
+impl<'a, D: Drive + Clone> Stream for IncomingMultishot<'a, D> {
+    type Item = io::Result<TcpStream<D>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let next = ready!(self.socket.as_mut().poll_accept_multishot(ctx));
+        Poll::Ready(Some(next))
+    }
+}
+
 pub struct Close<'a, D: Drive> {
     socket: Pin<&'a mut TcpListener<D>>,
 }
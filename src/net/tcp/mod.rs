@@ -3,8 +3,8 @@
 mod listener;
 mod stream;
 
-pub use listener::{TcpListener, Accept, AcceptNoAddr, Close, Incoming, IncomingNoAddr};
-pub use stream::{TcpStream, Connect};
+pub use listener::{TcpListener, Accept, AcceptNoAddr, Close, Incoming, IncomingNoAddr, IncomingMultishot};
+pub use stream::{TcpStream, Connect, Shutdown};
 
 use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
@@ -12,6 +12,24 @@ use std::os::unix::io::RawFd;
 
 use nix::sys::socket as nix_socket;
 
+// Shared by `TcpStream::local_addr`/`peer_addr` and
+// `TcpListener::local_addr`: these are cheap synchronous calls, so they
+// don't need to go through the ring.
+fn local_addr(fd: RawFd) -> io::Result<SocketAddr> {
+    sockaddr_to_std(nix_socket::getsockname(fd))
+}
+
+fn peer_addr(fd: RawFd) -> io::Result<SocketAddr> {
+    sockaddr_to_std(nix_socket::getpeername(fd))
+}
+
+fn sockaddr_to_std(result: nix::Result<nix_socket::SockAddr>) -> io::Result<SocketAddr> {
+    match result.map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO))? {
+        nix_socket::SockAddr::Inet(addr)   => Ok(addr.to_std()),
+        addr                                => panic!("tcp socket addr cannot be {:?}", addr.family()),
+    }
+}
+
 fn socket<A: ToSocketAddrs>(addr: A, protocol: nix_socket::SockProtocol) -> io::Result<(RawFd, SocketAddr)> {
     use io::{Error, ErrorKind};
 
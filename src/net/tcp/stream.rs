@@ -1,14 +1,15 @@
 use std::io;
 use std::future::Future;
-use std::net::ToSocketAddrs;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::os::unix::io::RawFd;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures_core::ready;
 use futures_io::{AsyncRead, AsyncBufRead, AsyncWrite};
-use iou::sqe::SockAddr;
-use nix::sys::socket::SockProtocol;
+use iou::sqe::{SockAddr, SubmissionFlags, Timespec, TimeoutFlags};
+use nix::sys::socket::{self as nix_socket, SockProtocol};
 
 use crate::buf::Buffer;
 use crate::drive::{Drive, demo::DemoDriver};
@@ -23,6 +24,14 @@ pub struct TcpStream<D: Drive = DemoDriver> {
     buf: Buffer,
     active: Op,
     fd: RawFd,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    // Owned kernel timespec for whichever read or write is currently in
+    // flight with a timeout attached. It has to live at a stable address
+    // for as long as the linked SQE pair is outstanding, so it's carried on
+    // the stream rather than the call stack (mirroring how `addr` is owned
+    // by `TcpListener` across an in-flight accept).
+    timeout: Option<Box<Timespec>>,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -30,6 +39,7 @@ enum Op {
     Read,
     Write,
     Close,
+    Shutdown,
     Nothing,
     Closed,
 }
@@ -38,6 +48,10 @@ impl TcpStream {
     pub fn connect<A: ToSocketAddrs>(addr: A) -> Connect {
         TcpStream::connect_on_driver(addr, DemoDriver::default())
     }
+
+    pub fn connect_timeout<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Connect {
+        TcpStream::connect_timeout_on_driver(addr, timeout, DemoDriver::default())
+    }
 }
 
 impl<D: Drive + Clone> TcpStream<D> {
@@ -47,7 +61,17 @@ impl<D: Drive + Clone> TcpStream<D> {
             Err(e)  => return Connect(Err(Some(e))),
         };
         let addr = Box::new(SockAddr::Inet(nix::sys::socket::InetAddr::from_std(&addr)));
-        Connect(Ok(driver.submit(event::Connect { fd, addr })))
+        Connect(Ok(driver.submit(event::Connect { fd, addr, timeout: None })))
+    }
+
+    pub fn connect_timeout_on_driver<A: ToSocketAddrs>(addr: A, timeout: Duration, driver: D) -> Connect<D> {
+        let (fd, addr) = match socket(addr, SockProtocol::Tcp) {
+            Ok(fd)  => fd,
+            Err(e)  => return Connect(Err(Some(e))),
+        };
+        let addr = Box::new(SockAddr::Inet(nix::sys::socket::InetAddr::from_std(&addr)));
+        let timeout = Some(Box::new(timespec(timeout)));
+        Connect(Ok(driver.submit(event::Connect { fd, addr, timeout })))
     }
 }
 
@@ -56,10 +80,68 @@ impl<D: Drive> TcpStream<D> {
         TcpStream {
             buf: Buffer::default(),
             active: Op::Nothing,
+            read_timeout: None,
+            write_timeout: None,
+            timeout: None,
             fd, ring,
         }
     }
 
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        super::local_addr(self.fd)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        super::peer_addr(self.fd)
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        nix_socket::getsockopt(self.fd, nix_socket::sockopt::TcpNoDelay)
+            .map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
+    pub fn set_nodelay(&self, enabled: bool) -> io::Result<()> {
+        nix_socket::setsockopt(self.fd, nix_socket::sockopt::TcpNoDelay, &enabled)
+            .map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        nix_socket::getsockopt(self.fd, nix_socket::sockopt::IpTtl)
+            .map(|ttl| ttl as u32)
+            .map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        nix_socket::setsockopt(self.fd, nix_socket::sockopt::IpTtl, &(ttl as libc::c_int))
+            .map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
+    pub fn keepalive(&self) -> io::Result<bool> {
+        nix_socket::getsockopt(self.fd, nix_socket::sockopt::SoKeepAlive)
+            .map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
+    pub fn set_keepalive(&self, enabled: bool) -> io::Result<()> {
+        nix_socket::setsockopt(self.fd, nix_socket::sockopt::SoKeepAlive, &enabled)
+            .map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
     fn guard_op(self: Pin<&mut Self>, op: Op) {
         let (ring, buf, active) = self.split();
         if *active == Op::Closed {
@@ -72,6 +154,9 @@ impl<D: Drive> TcpStream<D> {
 
     fn cancel(&mut self) {
         self.active = Op::Nothing;
+        if let Some(timeout) = self.timeout.take() {
+            self.ring.cancel(crate::ring::Cancellation::from(timeout));
+        }
         self.ring.cancel(self.buf.cancellation());
     }
 
@@ -96,6 +181,65 @@ impl<D: Drive> TcpStream<D> {
     fn confirm_close(self: Pin<&mut Self>) {
         *self.split().2 = Op::Closed;
     }
+
+    fn confirm_shutdown(self: Pin<&mut Self>) {
+        *self.split().2 = Op::Nothing;
+    }
+
+    pub fn shutdown(&mut self, how: std::net::Shutdown) -> Shutdown<'_, D> where D: Unpin {
+        Pin::new(self).shutdown_pinned(how)
+    }
+
+    pub fn shutdown_pinned(self: Pin<&mut Self>, how: std::net::Shutdown) -> Shutdown<'_, D> {
+        Shutdown { socket: self, how }
+    }
+
+    pub fn poll_shutdown(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, how: std::net::Shutdown)
+        -> Poll<io::Result<()>>
+    {
+        self.as_mut().guard_op(Op::Shutdown);
+        let fd = self.fd;
+        ready!(self.as_mut().ring().poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe {
+                sqe.prep_shutdown(fd, match how {
+                    std::net::Shutdown::Read   => libc::SHUT_RD,
+                    std::net::Shutdown::Write  => libc::SHUT_WR,
+                    std::net::Shutdown::Both   => libc::SHUT_RDWR,
+                });
+            }
+            sqe
+        }))?;
+        // Unlike `poll_close`, shutdown only tears down one or both
+        // directions of the socket at the protocol level; the fd stays
+        // open, so the op goes back to `Nothing` rather than `Closed`.
+        self.as_mut().confirm_shutdown();
+        Poll::Ready(Ok(()))
+    }
+
+    // Lazily (re)initializes the owned timespec used by a linked
+    // IORING_OP_LINK_TIMEOUT SQE for the operation about to be submitted,
+    // returning `None` when no timeout is configured for this op.
+    fn split_with_timeout(self: Pin<&mut Self>, timeout: Option<Duration>)
+        -> (Pin<&mut Ring<D>>, &mut Buffer, Option<&mut Timespec>)
+    {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            let ts = match timeout {
+                Some(duration) => {
+                    let ts = this.timeout.get_or_insert_with(|| Box::new(timespec(duration)));
+                    **ts = timespec(duration);
+                    Some(&mut **ts)
+                }
+                None => { this.timeout = None; None }
+            };
+            (Pin::new_unchecked(&mut this.ring), &mut this.buf, ts)
+        }
+    }
+}
+
+fn timespec(duration: Duration) -> Timespec {
+    Timespec::new().sec(duration.as_secs()).nsec(duration.subsec_nanos())
 }
 
 pub struct Connect<D: Drive = DemoDriver>(
@@ -109,7 +253,7 @@ impl<D: Drive + Clone> Future for Connect<D> {
         match self.project() {
             Ok(mut submission)  => {
                 let (connect, result) = ready!(submission.as_mut().poll(ctx));
-                result?;
+                result.map_err(event::map_timeout)?;
                 let driver = submission.driver().clone();
                 Poll::Ready(Ok(TcpStream::from_fd(connect.fd, Ring::new(driver))))
             }
@@ -149,15 +293,29 @@ impl<D: Drive> AsyncBufRead for TcpStream<D> {
     fn poll_fill_buf(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
         self.as_mut().guard_op(Op::Read);
         let fd = self.fd;
-        let (ring, buf, ..) = self.split();
+        let read_timeout = self.read_timeout;
+        let (ring, buf, timeout) = self.split_with_timeout(read_timeout);
+        let sqes_needed = if timeout.is_some() { 2 } else { 1 };
         buf.fill_buf(|buf| {
-            let n = ready!(ring.poll(ctx, 1, |sqs| { 
-                let mut sqe = sqs.single().unwrap();
+            let n = ready!(ring.poll(ctx, sqes_needed, |sqs| {
                 unsafe {
-                    sqe.prep_read(fd, buf, 0);
+                    match &timeout {
+                        Some(timeout) => {
+                            let mut read = sqs.next().unwrap();
+                            read.prep_read(fd, buf, 0);
+                            read.set_flags(SubmissionFlags::IO_LINK);
+                            let mut link_timeout = sqs.next().unwrap();
+                            link_timeout.prep_link_timeout(timeout, TimeoutFlags::empty());
+                            read
+                        }
+                        None => {
+                            let mut sqe = sqs.single().unwrap();
+                            sqe.prep_read(fd, buf, 0);
+                            sqe
+                        }
+                    }
                 }
-                sqe
-            }))?;
+            })).map_err(event::map_timeout)?;
             Poll::Ready(Ok(n as u32))
         })
     }
@@ -171,17 +329,31 @@ impl<D: Drive> AsyncWrite for TcpStream<D> {
     fn poll_write(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, slice: &[u8]) -> Poll<io::Result<usize>> {
         self.as_mut().guard_op(Op::Write);
         let fd = self.fd;
-        let (ring, buf, ..) = self.split();
+        let write_timeout = self.write_timeout;
+        let (ring, buf, timeout) = self.split_with_timeout(write_timeout);
+        let sqes_needed = if timeout.is_some() { 2 } else { 1 };
         let data = ready!(buf.fill_buf(|mut buf| {
             Poll::Ready(Ok(io::Write::write(&mut buf, slice)? as u32))
         }))?;
-        let n = ready!(ring.poll(ctx, 1, |sqs| {
-            let mut sqe = sqs.single().unwrap();
+        let n = ready!(ring.poll(ctx, sqes_needed, |sqs| {
             unsafe {
-                sqe.prep_write(fd, data, 0);
+                match &timeout {
+                    Some(timeout) => {
+                        let mut write = sqs.next().unwrap();
+                        write.prep_write(fd, data, 0);
+                        write.set_flags(SubmissionFlags::IO_LINK);
+                        let mut link_timeout = sqs.next().unwrap();
+                        link_timeout.prep_link_timeout(timeout, TimeoutFlags::empty());
+                        write
+                    }
+                    None => {
+                        let mut sqe = sqs.single().unwrap();
+                        sqe.prep_write(fd, data, 0);
+                        sqe
+                    }
+                }
             }
-            sqe
-        }))?;
+        })).map_err(event::map_timeout)?;
         buf.clear();
         Poll::Ready(Ok(n as usize))
     }
@@ -215,3 +387,17 @@ impl<D: Drive> Drop for TcpStream<D> {
         }
     }
 }
+
+pub struct Shutdown<'a, D: Drive> {
+    socket: Pin<&'a mut TcpStream<D>>,
+    how: std::net::Shutdown,
+}
+
+impl<'a, D: Drive> Future for Shutdown<'a, D> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let how = self.how;
+        self.socket.as_mut().poll_shutdown(ctx, how)
+    }
+}
@@ -0,0 +1,426 @@
+use std::io;
+use std::future::Future;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::io::RawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::ready;
+use iou::sqe::{SockAddr, SockAddrStorage};
+use nix::sys::socket::{self as nix_socket, InetAddr};
+
+use crate::drive::{Drive, demo::DemoDriver};
+use crate::ring::{Cancellation, Ring};
+
+pub struct UdpSocket<D: Drive = DemoDriver> {
+    ring: Ring<D>,
+    fd: RawFd,
+    active: Op,
+    recv: Option<Box<RecvMsg>>,
+    send: Option<Box<SendMsg>>,
+    // Plain `send`/`recv` (as opposed to `send_to`/`recv_from`) don't need
+    // a msghdr, just the raw buffer — but it still has to be owned across
+    // polls rather than kept in a stack local, since the kernel holds a
+    // pointer into it for as long as the SQE is in flight.
+    read: Option<Vec<u8>>,
+    write: Option<Vec<u8>>,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+enum Op {
+    Nothing = 0,
+    Send,
+    SendTo,
+    Recv,
+    RecvFrom,
+    Close,
+    Closed,
+}
+
+// Owned msghdr/iovec/buffer for a recvmsg, mirroring how `TcpListener` owns
+// its `Box<SockAddrStorage>` across an in-flight accept: the kernel holds a
+// pointer into this for the whole lifetime of the op, so it has to live at
+// a stable address and be carried through `Cancellation` if the op is
+// abandoned mid-flight.
+struct RecvMsg {
+    hdr: libc::msghdr,
+    iov: libc::iovec,
+    addr: SockAddrStorage,
+    buf: Vec<u8>,
+}
+
+impl RecvMsg {
+    fn new(buf: Vec<u8>) -> Box<RecvMsg> {
+        let mut msg = Box::new(RecvMsg {
+            hdr: unsafe { std::mem::zeroed() },
+            iov: libc::iovec { iov_base: std::ptr::null_mut(), iov_len: 0 },
+            addr: SockAddrStorage::uninit(),
+            buf,
+        });
+        msg.iov.iov_base = msg.buf.as_mut_ptr() as *mut libc::c_void;
+        msg.iov.iov_len = msg.buf.len();
+        let iov: *mut libc::iovec = &mut msg.iov;
+        let addr: *mut SockAddrStorage = &mut msg.addr;
+        msg.hdr.msg_iov = iov;
+        msg.hdr.msg_iovlen = 1;
+        msg.hdr.msg_name = addr as *mut libc::c_void;
+        msg.hdr.msg_namelen = std::mem::size_of::<SockAddrStorage>() as u32;
+        msg
+    }
+}
+
+// Same idea as `RecvMsg`, but `addr` is the destination supplied by the
+// caller rather than storage for the kernel to fill in.
+struct SendMsg {
+    hdr: libc::msghdr,
+    iov: libc::iovec,
+    addr: SockAddr,
+    buf: Vec<u8>,
+}
+
+impl SendMsg {
+    fn new(buf: Vec<u8>, addr: SockAddr) -> Box<SendMsg> {
+        let mut msg = Box::new(SendMsg {
+            hdr: unsafe { std::mem::zeroed() },
+            iov: libc::iovec { iov_base: std::ptr::null_mut(), iov_len: 0 },
+            addr,
+            buf,
+        });
+        msg.iov.iov_base = msg.buf.as_ptr() as *mut libc::c_void;
+        msg.iov.iov_len = msg.buf.len();
+        let (addr_ptr, addr_len) = msg.addr.as_ffi_pair();
+        let iov: *mut libc::iovec = &mut msg.iov;
+        msg.hdr.msg_iov = iov;
+        msg.hdr.msg_iovlen = 1;
+        msg.hdr.msg_name = addr_ptr as *mut libc::c_void;
+        msg.hdr.msg_namelen = addr_len;
+        msg
+    }
+}
+
+impl UdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+        UdpSocket::bind_on_driver(addr, DemoDriver::default())
+    }
+}
+
+impl<D: Drive> UdpSocket<D> {
+    pub fn bind_on_driver<A: ToSocketAddrs>(addr: A, driver: D) -> io::Result<UdpSocket<D>> {
+        let (fd, addr) = super::socket(addr)?;
+        let sockaddr = SockAddr::Inet(InetAddr::from_std(&addr));
+        nix_socket::bind(fd, &sockaddr).map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO))?;
+        Ok(UdpSocket {
+            ring: Ring::new(driver),
+            active: Op::Nothing,
+            recv: None,
+            send: None,
+            read: None,
+            write: None,
+            fd,
+        })
+    }
+
+    /// Fixes the default peer for `send`/`recv`. Unlike TCP, this is a
+    /// synchronous, non-blocking call for a datagram socket, so it doesn't
+    /// need to go through the ring.
+    pub fn connect<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
+        let addr = addr.to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses"))?;
+        let sockaddr = SockAddr::Inet(InetAddr::from_std(&addr));
+        nix_socket::connect(self.fd, &sockaddr).map_err(|e| e.as_errno().unwrap_or(nix::errno::Errno::EIO).into())
+    }
+
+    pub fn close(&mut self) -> Close<'_, D> where D: Unpin {
+        Pin::new(self).close_pinned()
+    }
+
+    pub fn close_pinned(self: Pin<&mut Self>) -> Close<'_, D> {
+        Close { socket: self }
+    }
+
+    fn guard_op(self: Pin<&mut Self>, op: Op) {
+        let this = self.mut_unpinned();
+        if this.active == Op::Closed {
+            panic!("Attempted to perform IO on a closed UdpSocket");
+        } else if this.active != Op::Nothing && this.active != op {
+            this.cancel_active();
+        }
+        this.active = op;
+    }
+
+    fn cancel_active(&mut self) {
+        let cancellation = match self.active {
+            Op::Send | Op::Recv | Op::SendTo | Op::RecvFrom => self.take_cancellation(),
+            Op::Close  => Cancellation::from(()),
+            Op::Closed | Op::Nothing => return,
+        };
+        self.active = Op::Nothing;
+        self.ring.cancel(cancellation);
+    }
+
+    fn take_cancellation(&mut self) -> Cancellation {
+        match (self.recv.take(), self.send.take(), self.read.take(), self.write.take()) {
+            (Some(recv), ..)       => Cancellation::from(recv),
+            (_, Some(send), ..)    => Cancellation::from(send),
+            (_, _, Some(read), _)  => Cancellation::from(read),
+            (_, _, _, Some(write)) => Cancellation::from(write),
+            _                      => Cancellation::from(()),
+        }
+    }
+
+    fn mut_unpinned(self: Pin<&mut Self>) -> &mut Self {
+        unsafe { Pin::get_unchecked_mut(self) }
+    }
+
+    fn ring(self: Pin<&mut Self>) -> Pin<&mut Ring<D>> {
+        unsafe { Pin::new_unchecked(&mut self.mut_unpinned().ring) }
+    }
+
+    fn split_write(self: Pin<&mut Self>) -> (Pin<&mut Ring<D>>, &mut Option<Vec<u8>>) {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            (Pin::new_unchecked(&mut this.ring), &mut this.write)
+        }
+    }
+
+    fn split_read(self: Pin<&mut Self>) -> (Pin<&mut Ring<D>>, &mut Option<Vec<u8>>) {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            (Pin::new_unchecked(&mut this.ring), &mut this.read)
+        }
+    }
+
+    fn split_send(self: Pin<&mut Self>) -> (Pin<&mut Ring<D>>, &mut Option<Box<SendMsg>>) {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            (Pin::new_unchecked(&mut this.ring), &mut this.send)
+        }
+    }
+
+    fn split_recv(self: Pin<&mut Self>) -> (Pin<&mut Ring<D>>, &mut Option<Box<RecvMsg>>) {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            (Pin::new_unchecked(&mut this.ring), &mut this.recv)
+        }
+    }
+
+    fn confirm_close(self: Pin<&mut Self>) {
+        self.mut_unpinned().active = Op::Closed;
+    }
+}
+
+impl<D: Drive> UdpSocket<D> {
+    pub fn send(&mut self, buf: Vec<u8>) -> Send<'_, D> where D: Unpin {
+        Pin::new(self).send_pinned(buf)
+    }
+
+    pub fn send_pinned(self: Pin<&mut Self>, buf: Vec<u8>) -> Send<'_, D> {
+        Send { socket: self, buf: Some(buf) }
+    }
+
+    pub fn poll_send(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, buf: &mut Option<Vec<u8>>)
+        -> Poll<io::Result<(Vec<u8>, usize)>>
+    {
+        self.as_mut().guard_op(Op::Send);
+        let fd = self.as_mut().mut_unpinned().fd;
+        if self.as_mut().mut_unpinned().write.is_none() {
+            self.as_mut().mut_unpinned().write = buf.take();
+        }
+        let (ring, write) = self.as_mut().split_write();
+        let data = write.as_ref().expect("polled Send after completion");
+        let n = ready!(ring.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_write(fd, data, 0); }
+            sqe
+        }));
+        let data = self.as_mut().mut_unpinned().write.take().unwrap();
+        match n {
+            Ok(n)  => Poll::Ready(Ok((data, n as usize))),
+            Err(e) => { self.as_mut().mut_unpinned().write = Some(data); Poll::Ready(Err(e)) }
+        }
+    }
+
+    pub fn recv(&mut self, buf: Vec<u8>) -> Recv<'_, D> where D: Unpin {
+        Pin::new(self).recv_pinned(buf)
+    }
+
+    pub fn recv_pinned(self: Pin<&mut Self>, buf: Vec<u8>) -> Recv<'_, D> {
+        Recv { socket: self, buf: Some(buf) }
+    }
+
+    pub fn poll_recv(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, buf: &mut Option<Vec<u8>>)
+        -> Poll<io::Result<(Vec<u8>, usize)>>
+    {
+        self.as_mut().guard_op(Op::Recv);
+        let fd = self.as_mut().mut_unpinned().fd;
+        if self.as_mut().mut_unpinned().read.is_none() {
+            self.as_mut().mut_unpinned().read = buf.take();
+        }
+        let (ring, read) = self.as_mut().split_read();
+        let data = read.as_mut().expect("polled Recv after completion");
+        let n = ready!(ring.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_read(fd, data, 0); }
+            sqe
+        }));
+        let data = self.as_mut().mut_unpinned().read.take().unwrap();
+        match n {
+            Ok(n)  => Poll::Ready(Ok((data, n as usize))),
+            Err(e) => { self.as_mut().mut_unpinned().read = Some(data); Poll::Ready(Err(e)) }
+        }
+    }
+
+    pub fn send_to<A: ToSocketAddrs>(&mut self, buf: Vec<u8>, addr: A) -> io::Result<SendTo<'_, D>> where D: Unpin {
+        Pin::new(self).send_to_pinned(buf, addr)
+    }
+
+    pub fn send_to_pinned<A: ToSocketAddrs>(self: Pin<&mut Self>, buf: Vec<u8>, addr: A) -> io::Result<SendTo<'_, D>> {
+        let addr = addr.to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses"))?;
+        Ok(SendTo { socket: self, msg: Some((buf, SockAddr::Inet(InetAddr::from_std(&addr)))) })
+    }
+
+    pub fn poll_send_to(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+        pending: &mut Option<(Vec<u8>, SockAddr)>,
+    ) -> Poll<io::Result<(Vec<u8>, usize)>> {
+        self.as_mut().guard_op(Op::SendTo);
+        let fd = self.as_mut().mut_unpinned().fd;
+        if self.as_mut().mut_unpinned().send.is_none() {
+            let (buf, addr) = pending.take().expect("polled SendTo after completion");
+            self.as_mut().mut_unpinned().send = Some(SendMsg::new(buf, addr));
+        }
+        let (ring, send) = self.as_mut().split_send();
+        let msg = send.as_mut().unwrap();
+        let n = ready!(ring.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_sendmsg(fd, &mut msg.hdr, iou::sqe::MsgFlags::empty()); }
+            sqe
+        }));
+        let msg = self.as_mut().mut_unpinned().send.take().unwrap();
+        Poll::Ready(n.map(|n| (msg.buf, n as usize)))
+    }
+
+    pub fn recv_from(&mut self, buf: Vec<u8>) -> RecvFrom<'_, D> where D: Unpin {
+        Pin::new(self).recv_from_pinned(buf)
+    }
+
+    pub fn recv_from_pinned(self: Pin<&mut Self>, buf: Vec<u8>) -> RecvFrom<'_, D> {
+        RecvFrom { socket: self, buf: Some(buf) }
+    }
+
+    pub fn poll_recv_from(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, pending: &mut Option<Vec<u8>>)
+        -> Poll<io::Result<(Vec<u8>, usize, SocketAddr)>>
+    {
+        self.as_mut().guard_op(Op::RecvFrom);
+        let fd = self.as_mut().mut_unpinned().fd;
+        if self.as_mut().mut_unpinned().recv.is_none() {
+            let buf = pending.take().expect("polled RecvFrom after completion");
+            self.as_mut().mut_unpinned().recv = Some(RecvMsg::new(buf));
+        }
+        let (ring, recv) = self.as_mut().split_recv();
+        let msg = recv.as_mut().unwrap();
+        let n = ready!(ring.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_recvmsg(fd, &mut msg.hdr, iou::sqe::MsgFlags::empty()); }
+            sqe
+        }));
+        let n = match n {
+            Ok(n)   => n,
+            Err(e)  => { self.as_mut().mut_unpinned().recv = None; return Poll::Ready(Err(e)); }
+        };
+        let msg = self.as_mut().mut_unpinned().recv.take().unwrap();
+        let addr = match unsafe { msg.addr.as_socket_addr() }? {
+            SockAddr::Inet(addr) => addr.to_std(),
+            addr => panic!("UdpSocket peer addr cannot be {:?}", addr.family()),
+        };
+        Poll::Ready(Ok((msg.buf, n as usize, addr)))
+    }
+}
+
+impl<D: Drive> Drop for UdpSocket<D> {
+    fn drop(&mut self) {
+        match self.active {
+            Op::Closed  => { }
+            Op::Nothing => unsafe { libc::close(self.fd); },
+            _           => self.cancel_active(),
+        }
+    }
+}
+
+pub struct Send<'a, D: Drive> {
+    socket: Pin<&'a mut UdpSocket<D>>,
+    buf: Option<Vec<u8>>,
+}
+
+impl<'a, D: Drive> Future for Send<'a, D> {
+    type Output = io::Result<(Vec<u8>, usize)>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { socket, buf } = unsafe { Pin::get_unchecked_mut(self.as_mut()) };
+        socket.as_mut().poll_send(ctx, buf)
+    }
+}
+
+pub struct Recv<'a, D: Drive> {
+    socket: Pin<&'a mut UdpSocket<D>>,
+    buf: Option<Vec<u8>>,
+}
+
+impl<'a, D: Drive> Future for Recv<'a, D> {
+    type Output = io::Result<(Vec<u8>, usize)>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { socket, buf } = unsafe { Pin::get_unchecked_mut(self.as_mut()) };
+        socket.as_mut().poll_recv(ctx, buf)
+    }
+}
+
+pub struct SendTo<'a, D: Drive> {
+    socket: Pin<&'a mut UdpSocket<D>>,
+    msg: Option<(Vec<u8>, SockAddr)>,
+}
+
+impl<'a, D: Drive> Future for SendTo<'a, D> {
+    type Output = io::Result<(Vec<u8>, usize)>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { socket, msg } = unsafe { Pin::get_unchecked_mut(self.as_mut()) };
+        socket.as_mut().poll_send_to(ctx, msg)
+    }
+}
+
+pub struct RecvFrom<'a, D: Drive> {
+    socket: Pin<&'a mut UdpSocket<D>>,
+    buf: Option<Vec<u8>>,
+}
+
+impl<'a, D: Drive> Future for RecvFrom<'a, D> {
+    type Output = io::Result<(Vec<u8>, usize, SocketAddr)>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { socket, buf } = unsafe { Pin::get_unchecked_mut(self.as_mut()) };
+        socket.as_mut().poll_recv_from(ctx, buf)
+    }
+}
+
+pub struct Close<'a, D: Drive> {
+    socket: Pin<&'a mut UdpSocket<D>>,
+}
+
+impl<'a, D: Drive> Future for Close<'a, D> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.socket.as_mut().guard_op(Op::Close);
+        let fd = self.socket.as_mut().mut_unpinned().fd;
+        ready!(self.socket.as_mut().ring().poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_close(fd); }
+            sqe
+        }))?;
+        self.socket.as_mut().confirm_close();
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,94 @@
+//! `Ring`: the per-socket handle used to submit individual io_uring
+//! operations against a `Drive` and resolve their completions.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::ready;
+
+use crate::drive::Drive;
+use crate::event::{SQE, SQEs};
+
+// Set by the kernel on a CQE to say more completions are coming for the
+// SQE that produced it (e.g. an accept armed with
+// `IORING_ACCEPT_MULTISHOT`). Once a completion arrives without it, the
+// op has stopped and has to be resubmitted (re-armed) to keep producing
+// results.
+const IORING_CQE_F_MORE: u32 = 1 << 1;
+
+/// Something that has to stay alive until a cancelled operation's SQE is
+/// no longer in flight, because the kernel may still be reading from (or
+/// writing into) it. Dropping a `Cancellation` drops whatever it's
+/// holding.
+pub struct Cancellation {
+    _inner: Box<dyn std::any::Any>,
+}
+
+impl<T: 'static> From<T> for Cancellation {
+    fn from(inner: T) -> Cancellation {
+        Cancellation { _inner: Box::new(inner) }
+    }
+}
+
+/// A handle for submitting individual io_uring operations against a
+/// particular `Drive` and resolving their completions.
+#[derive(Clone)]
+pub struct Ring<D> {
+    driver: D,
+}
+
+impl<D: Drive> Ring<D> {
+    pub fn new(driver: D) -> Ring<D> {
+        Ring { driver }
+    }
+
+    /// Submit `count` SQEs prepared by `prepare`, resolving to the `res`
+    /// field of the resulting CQE.
+    pub fn poll(
+        self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+        count: u32,
+        prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>,
+    ) -> Poll<io::Result<i32>> {
+        let driver = unsafe { &mut Pin::get_unchecked_mut(self).driver };
+        driver.poll(ctx, count, prepare)
+    }
+
+    /// Like `poll`, but for an op the kernel may complete more than once.
+    /// `armed` should be `false` the first time a logical operation is
+    /// polled (so `prepare` is called to submit it) and `true` on every
+    /// later poll that's just waiting on a submission that's already
+    /// live.
+    ///
+    /// Resolves to the CQE's `res` alongside whether the kernel will keep
+    /// completing this op: `true` means poll again with `armed: true`;
+    /// `false` means the op has stopped, and the next poll has to re-arm
+    /// it by passing `armed: false`.
+    ///
+    /// The `bool` is returned outside the `Result` because it has to be
+    /// read on an error completion too: an op can fail (or hit resource
+    /// pressure) on a CQE that doesn't carry `IORING_CQE_F_MORE`, meaning
+    /// the kernel has already dropped the registration even though this
+    /// call resolves to `Err`. A caller must still re-arm on the next
+    /// poll in that case, so it cannot tell from the `Result` alone.
+    pub fn poll_multishot(
+        self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+        armed: bool,
+        count: u32,
+        prepare: impl FnOnce(&mut SQEs<'_>) -> SQE<'_>,
+    ) -> Poll<(io::Result<i32>, bool)> {
+        let driver = unsafe { &mut Pin::get_unchecked_mut(self).driver };
+        let (result, flags) = ready!(driver.poll_multishot(ctx, armed, count, prepare));
+        Poll::Ready((result, flags & IORING_CQE_F_MORE != 0))
+    }
+
+    pub fn cancel(&mut self, cancellation: Cancellation) {
+        self.driver.cancel(cancellation);
+    }
+
+    pub fn cancel_pinned(self: Pin<&mut Self>, cancellation: Cancellation) {
+        unsafe { Pin::get_unchecked_mut(self) }.cancel(cancellation);
+    }
+}
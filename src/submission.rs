@@ -0,0 +1,46 @@
+//! `Submission`: drives a single one-shot `Event` through a `Drive` to
+//! completion.
+
+use std::io;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::ready;
+
+use crate::drive::Drive;
+use crate::event::Event;
+
+pub struct Submission<E, D> {
+    event: Option<E>,
+    driver: D,
+}
+
+impl<E: Event, D: Drive> Submission<E, D> {
+    pub(crate) fn new(event: E, driver: D) -> Submission<E, D> {
+        Submission { event: Some(event), driver }
+    }
+
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+
+    pub fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<(E, io::Result<i32>)> {
+        let this = unsafe { Pin::get_unchecked_mut(self) };
+        let result = {
+            let event = this.event.as_mut().expect("Submission polled after it already completed");
+            ready!(this.driver.poll(ctx, event.sqes_needed(), |sqs| unsafe { event.prepare(sqs) }))
+        };
+        let event = this.event.take().unwrap();
+        Poll::Ready((event, result))
+    }
+}
+
+impl<E: Event, D: Drive> Drop for Submission<E, D> {
+    fn drop(&mut self) {
+        if let Some(event) = self.event.take() {
+            let cancellation = Event::cancel(ManuallyDrop::new(event));
+            self.driver.cancel(cancellation);
+        }
+    }
+}
@@ -5,7 +5,7 @@ mod listener;
 mod stream;
 
 pub use listener::{UnixListener, Close, Accept, Incoming};
-pub use stream::{UnixStream, Connect};
+pub use stream::{UnixStream, Connect, SendFds, RecvFds};
 
 use nix::sys::socket as nix_socket;
 
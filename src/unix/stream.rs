@@ -0,0 +1,421 @@
+use std::io;
+use std::future::Future;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::ready;
+use futures_io::{AsyncRead, AsyncBufRead, AsyncWrite};
+use iou::sqe::{SockAddr, MsgFlags};
+use nix::sys::socket as nix_socket;
+
+use crate::buf::Buffer;
+use crate::drive::{Drive, demo::DemoDriver};
+use crate::ring::{Cancellation, Ring};
+use crate::event;
+use crate::Submission;
+
+pub struct UnixStream<D: Drive = DemoDriver> {
+    ring: Ring<D>,
+    buf: Buffer,
+    active: Op,
+    fd: RawFd,
+    send_fds: Option<Box<SendFdsMsg>>,
+    recv_fds: Option<Box<RecvFdsMsg>>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Op {
+    Read,
+    Write,
+    SendFds,
+    RecvFds,
+    Close,
+    Nothing,
+    Closed,
+}
+
+const FD_SIZE: usize = std::mem::size_of::<RawFd>();
+
+// Owned ancillary-data buffer for an SCM_RIGHTS sendmsg. The control buffer
+// and msghdr have to outlive the whole op, not just one `poll` call, so
+// (mirroring `TcpListener`'s owned accept address) they're carried on the
+// stream rather than built fresh on the stack each time.
+struct SendFdsMsg {
+    hdr: libc::msghdr,
+    iov: libc::iovec,
+    buf: Vec<u8>,
+    control: Vec<u8>,
+}
+
+impl SendFdsMsg {
+    fn new(buf: Vec<u8>, fds: &[RawFd]) -> Box<SendFdsMsg> {
+        let payload_len = (fds.len() * FD_SIZE) as u32;
+        let control_len = unsafe { libc::CMSG_SPACE(payload_len) };
+        let mut msg = Box::new(SendFdsMsg {
+            hdr: unsafe { std::mem::zeroed() },
+            iov: libc::iovec { iov_base: std::ptr::null_mut(), iov_len: 0 },
+            buf,
+            control: vec![0u8; control_len as usize],
+        });
+
+        msg.iov.iov_base = msg.buf.as_ptr() as *mut libc::c_void;
+        msg.iov.iov_len = msg.buf.len();
+        let iov: *mut libc::iovec = &mut msg.iov;
+        msg.hdr.msg_iov = iov;
+        msg.hdr.msg_iovlen = 1;
+        msg.hdr.msg_control = msg.control.as_mut_ptr() as *mut libc::c_void;
+        msg.hdr.msg_controllen = msg.control.len() as _;
+
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg.hdr);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(payload_len) as _;
+            std::ptr::copy_nonoverlapping(fds.as_ptr() as *const u8, libc::CMSG_DATA(cmsg), fds.len() * FD_SIZE);
+        }
+
+        msg
+    }
+}
+
+// Owned ancillary-data buffer for an SCM_RIGHTS recvmsg, sized up front for
+// `max_fds` received descriptors.
+struct RecvFdsMsg {
+    hdr: libc::msghdr,
+    iov: libc::iovec,
+    buf: Vec<u8>,
+    control: Vec<u8>,
+}
+
+impl RecvFdsMsg {
+    fn new(buf: Vec<u8>, max_fds: usize) -> Box<RecvFdsMsg> {
+        let control_len = unsafe { libc::CMSG_SPACE((max_fds * FD_SIZE) as u32) };
+        let mut msg = Box::new(RecvFdsMsg {
+            hdr: unsafe { std::mem::zeroed() },
+            iov: libc::iovec { iov_base: std::ptr::null_mut(), iov_len: 0 },
+            buf,
+            control: vec![0u8; control_len as usize],
+        });
+
+        msg.iov.iov_base = msg.buf.as_mut_ptr() as *mut libc::c_void;
+        msg.iov.iov_len = msg.buf.len();
+        let iov: *mut libc::iovec = &mut msg.iov;
+        msg.hdr.msg_iov = iov;
+        msg.hdr.msg_iovlen = 1;
+        msg.hdr.msg_control = msg.control.as_mut_ptr() as *mut libc::c_void;
+        msg.hdr.msg_controllen = msg.control.len() as _;
+
+        msg
+    }
+
+    // Parses the control buffer the kernel filled in, validating that it's
+    // really an SCM_RIGHTS message before handing back the data read and
+    // the fds the message named.
+    fn into_parts(self: Box<Self>) -> io::Result<(Vec<u8>, Vec<RawFd>)> {
+        let mut fds = Vec::new();
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&self.hdr);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let data = libc::CMSG_DATA(cmsg);
+                    let len = (*cmsg).cmsg_len as usize - (data as usize - cmsg as usize);
+                    let n = len / FD_SIZE;
+                    for i in 0..n {
+                        let mut fd: RawFd = 0;
+                        std::ptr::copy_nonoverlapping(data.add(i * FD_SIZE), &mut fd as *mut RawFd as *mut u8, FD_SIZE);
+                        fds.push(fd);
+                    }
+                } else {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "expected an SCM_RIGHTS control message"));
+                }
+                cmsg = libc::CMSG_NXTHDR(&self.hdr, cmsg);
+            }
+        }
+        Ok((self.buf, fds))
+    }
+}
+
+impl UnixStream {
+    pub fn connect<P: AsRef<Path>>(path: P) -> Connect {
+        UnixStream::connect_on_driver(path, DemoDriver::default())
+    }
+}
+
+impl<D: Drive + Clone> UnixStream<D> {
+    pub fn connect_on_driver<P: AsRef<Path>>(path: P, driver: D) -> Connect<D> {
+        let fd = match super::socket() {
+            Ok(fd)  => fd,
+            Err(e)  => return Connect(Err(Some(e))),
+        };
+        let addr = match nix_socket::UnixAddr::new(path.as_ref()) {
+            Ok(addr)    => Box::new(SockAddr::Unix(addr)),
+            Err(e)      => return Connect(Err(Some(io::Error::from(e.as_errno().unwrap_or(nix::errno::Errno::EIO))))),
+        };
+        Connect(Ok(driver.submit(event::Connect { fd, addr, timeout: None })))
+    }
+}
+
+impl<D: Drive> UnixStream<D> {
+    pub(crate) fn from_fd(fd: RawFd, ring: Ring<D>) -> UnixStream<D> {
+        UnixStream {
+            buf: Buffer::default(),
+            active: Op::Nothing,
+            send_fds: None,
+            recv_fds: None,
+            fd, ring,
+        }
+    }
+
+    fn guard_op(self: Pin<&mut Self>, op: Op) {
+        let this = self.mut_unpinned();
+        if this.active == Op::Closed {
+            panic!("Attempted to perform IO on a closed UnixStream");
+        } else if this.active != Op::Nothing && this.active != op {
+            this.cancel();
+        }
+        this.active = op;
+    }
+
+    fn cancel(&mut self) {
+        let cancellation = match self.active {
+            Op::SendFds => Cancellation::from(self.send_fds.take()),
+            Op::RecvFds => Cancellation::from(self.recv_fds.take()),
+            _           => Cancellation::from(self.buf.cancellation()),
+        };
+        self.active = Op::Nothing;
+        self.ring.cancel(cancellation);
+    }
+
+    fn mut_unpinned(self: Pin<&mut Self>) -> &mut Self {
+        unsafe { Pin::get_unchecked_mut(self) }
+    }
+
+    fn ring(self: Pin<&mut Self>) -> Pin<&mut Ring<D>> {
+        unsafe { Pin::new_unchecked(&mut self.mut_unpinned().ring) }
+    }
+
+    fn split_send_fds(self: Pin<&mut Self>) -> (Pin<&mut Ring<D>>, &mut Option<Box<SendFdsMsg>>) {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            (Pin::new_unchecked(&mut this.ring), &mut this.send_fds)
+        }
+    }
+
+    fn split_recv_fds(self: Pin<&mut Self>) -> (Pin<&mut Ring<D>>, &mut Option<Box<RecvFdsMsg>>) {
+        unsafe {
+            let this = Pin::get_unchecked_mut(self);
+            (Pin::new_unchecked(&mut this.ring), &mut this.recv_fds)
+        }
+    }
+
+    fn confirm_close(self: Pin<&mut Self>) {
+        self.mut_unpinned().active = Op::Closed;
+    }
+
+    pub fn send_fds(&mut self, buf: Vec<u8>, fds: &[RawFd]) -> SendFds<'_, D> where D: Unpin {
+        Pin::new(self).send_fds_pinned(buf, fds)
+    }
+
+    pub fn send_fds_pinned(self: Pin<&mut Self>, buf: Vec<u8>, fds: &[RawFd]) -> SendFds<'_, D> {
+        SendFds { socket: self, msg: Some(SendFdsMsg::new(buf, fds)) }
+    }
+
+    pub fn poll_send_fds(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, msg: &mut Option<Box<SendFdsMsg>>)
+        -> Poll<io::Result<usize>>
+    {
+        self.as_mut().guard_op(Op::SendFds);
+        let fd = self.as_mut().mut_unpinned().fd;
+        if self.as_mut().mut_unpinned().send_fds.is_none() {
+            self.as_mut().mut_unpinned().send_fds = msg.take();
+        }
+        let (ring, send_fds) = self.as_mut().split_send_fds();
+        let msg = send_fds.as_mut().unwrap();
+        let n = ready!(ring.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_sendmsg(fd, &mut msg.hdr, MsgFlags::empty()); }
+            sqe
+        }));
+        self.as_mut().mut_unpinned().send_fds = None;
+        Poll::Ready(n.map(|n| n as usize))
+    }
+
+    pub fn recv_fds(&mut self, buf: Vec<u8>, max_fds: usize) -> RecvFds<'_, D> where D: Unpin {
+        Pin::new(self).recv_fds_pinned(buf, max_fds)
+    }
+
+    pub fn recv_fds_pinned(self: Pin<&mut Self>, buf: Vec<u8>, max_fds: usize) -> RecvFds<'_, D> {
+        RecvFds { socket: self, msg: Some(RecvFdsMsg::new(buf, max_fds)) }
+    }
+
+    pub fn poll_recv_fds(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context<'_>,
+        pending: &mut Option<Box<RecvFdsMsg>>,
+    ) -> Poll<io::Result<(Vec<u8>, usize, Vec<RawFd>)>> {
+        self.as_mut().guard_op(Op::RecvFds);
+        let fd = self.as_mut().mut_unpinned().fd;
+        if self.as_mut().mut_unpinned().recv_fds.is_none() {
+            self.as_mut().mut_unpinned().recv_fds = pending.take();
+        }
+        let (ring, recv_fds) = self.as_mut().split_recv_fds();
+        let msg = recv_fds.as_mut().unwrap();
+        let n = ready!(ring.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            // MSG_CMSG_CLOEXEC makes the kernel install the received fds
+            // O_CLOEXEC atomically, closing the usual fork+exec leak window.
+            unsafe { sqe.prep_recvmsg(fd, &mut msg.hdr, MsgFlags::MSG_CMSG_CLOEXEC); }
+            sqe
+        }));
+        let n = match n {
+            Ok(n)   => n,
+            Err(e)  => { self.as_mut().mut_unpinned().recv_fds = None; return Poll::Ready(Err(e)); }
+        };
+        let msg = self.as_mut().mut_unpinned().recv_fds.take().unwrap();
+        match msg.into_parts() {
+            Ok((buf, fds)) => Poll::Ready(Ok((buf, n as usize, fds))),
+            Err(e)         => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+pub struct Connect<D: Drive = DemoDriver>(
+    Result<Submission<event::Connect, D>, Option<io::Error>>
+);
+
+impl<D: Drive + Clone> Future for Connect<D> {
+    type Output = io::Result<UnixStream<D>>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            Ok(mut submission)  => {
+                let (connect, result) = ready!(submission.as_mut().poll(ctx));
+                result.map_err(event::map_timeout)?;
+                let driver = submission.driver().clone();
+                Poll::Ready(Ok(UnixStream::from_fd(connect.fd, Ring::new(driver))))
+            }
+            Err(err)        => {
+                let err = err.take().expect("polled Connect future after completion");
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+impl<D: Drive> Connect<D> {
+    fn project(self: Pin<&mut Self>)
+        -> Result<Pin<&mut Submission<event::Connect, D>>, &mut Option<io::Error>>
+    {
+        unsafe {
+            match &mut Pin::get_unchecked_mut(self).0 {
+                Ok(submission)  => Ok(Pin::new_unchecked(submission)),
+                Err(err)        => Err(err)
+            }
+        }
+    }
+}
+
+impl<D: Drive> AsyncRead for UnixStream<D> {
+    fn poll_read(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        let mut inner = ready!(self.as_mut().poll_fill_buf(ctx))?;
+        let len = io::Read::read(&mut inner, buf)?;
+        self.consume(len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<D: Drive> AsyncBufRead for UnixStream<D> {
+    fn poll_fill_buf(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        self.as_mut().guard_op(Op::Read);
+        let fd = self.as_mut().mut_unpinned().fd;
+        let this = self.mut_unpinned();
+        this.buf.fill_buf(|buf| {
+            let n = ready!(unsafe { Pin::new_unchecked(&mut this.ring) }.poll(ctx, 1, |sqs| {
+                let mut sqe = sqs.single().unwrap();
+                unsafe { sqe.prep_read(fd, buf, 0); }
+                sqe
+            }))?;
+            Poll::Ready(Ok(n as u32))
+        })
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.mut_unpinned().buf.consume(amt);
+    }
+}
+
+impl<D: Drive> AsyncWrite for UnixStream<D> {
+    fn poll_write(mut self: Pin<&mut Self>, ctx: &mut Context<'_>, slice: &[u8]) -> Poll<io::Result<usize>> {
+        self.as_mut().guard_op(Op::Write);
+        let fd = self.as_mut().mut_unpinned().fd;
+        let this = self.mut_unpinned();
+        let data = ready!(this.buf.fill_buf(|mut buf| {
+            Poll::Ready(Ok(io::Write::write(&mut buf, slice)? as u32))
+        }))?;
+        let n = ready!(unsafe { Pin::new_unchecked(&mut this.ring) }.poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_write(fd, data, 0); }
+            sqe
+        }))?;
+        this.buf.clear();
+        Poll::Ready(Ok(n as usize))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write(ctx, &[]))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.as_mut().guard_op(Op::Close);
+        let fd = self.as_mut().mut_unpinned().fd;
+        ready!(self.as_mut().ring().poll(ctx, 1, |sqs| {
+            let mut sqe = sqs.single().unwrap();
+            unsafe { sqe.prep_close(fd); }
+            sqe
+        }))?;
+        self.confirm_close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<D: Drive> Drop for UnixStream<D> {
+    fn drop(&mut self) {
+        match self.active {
+            Op::Closed  => { }
+            Op::Nothing => unsafe { libc::close(self.fd); },
+            _           => self.cancel(),
+        }
+    }
+}
+
+pub struct SendFds<'a, D: Drive> {
+    socket: Pin<&'a mut UnixStream<D>>,
+    msg: Option<Box<SendFdsMsg>>,
+}
+
+impl<'a, D: Drive> Future for SendFds<'a, D> {
+    type Output = io::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { socket, msg } = unsafe { Pin::get_unchecked_mut(self.as_mut()) };
+        socket.as_mut().poll_send_fds(ctx, msg)
+    }
+}
+
+pub struct RecvFds<'a, D: Drive> {
+    socket: Pin<&'a mut UnixStream<D>>,
+    msg: Option<Box<RecvFdsMsg>>,
+}
+
+impl<'a, D: Drive> Future for RecvFds<'a, D> {
+    type Output = io::Result<(Vec<u8>, usize, Vec<RawFd>)>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self { socket, msg } = unsafe { Pin::get_unchecked_mut(self.as_mut()) };
+        socket.as_mut().poll_recv_fds(ctx, msg)
+    }
+}